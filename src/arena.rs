@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+
+use newick_parser::node::{FlatNode, FlatTree};
+
+use crate::SprError;
+
+/// A handle into a [`GenTree`]: a slot index plus the generation the slot
+/// had when this handle was issued. Once a slot is freed its generation is
+/// bumped, so a handle captured before the free is rejected by `get`
+/// instead of silently aliasing whatever node the recycled slot now holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeHandle {
+    slot: usize,
+    generation: u64,
+}
+
+/// The payload of a live `GenTree` slot: the same fields `FlatNode` carries,
+/// but with `NodeHandle`s in place of raw `usize` indices.
+#[derive(Debug, Clone)]
+pub struct GenNode {
+    pub name: String,
+    pub depth: Option<f64>,
+    pub parent: Option<NodeHandle>,
+    pub left_child: Option<NodeHandle>,
+    pub right_child: Option<NodeHandle>,
+}
+
+#[derive(Debug, Clone)]
+enum Slot {
+    Occupied { generation: u64, node: GenNode },
+    Free { generation: u64, next_free: Option<usize> },
+}
+
+/// Generational-arena-backed tree storage: an alternative to `FlatTree`'s
+/// plain `Vec` + raw index pairing, meant for long simulation runs that
+/// apply many transfers and would otherwise accumulate dead nodes with no
+/// way to reclaim their slots, or to detect a handle that outlived the
+/// node it pointed to. The designated root is tracked directly, so it is
+/// always known in O(1) without rescanning every node for a parentless
+/// entry after each `spr` call, which is how `main` drives the transfer
+/// loop.
+#[derive(Debug, Clone)]
+pub struct GenTree {
+    slots: Vec<Slot>,
+    free_head: Option<usize>,
+    root: NodeHandle,
+}
+
+impl GenTree {
+    /// Imports a `FlatTree` into arena-backed storage. Since a freshly
+    /// parsed/converted `FlatTree` has no gaps, every node starts at
+    /// generation 0 with a slot index equal to its original flat index.
+    pub fn from_flat_tree(flat_tree: &FlatTree) -> Self {
+        let handle_of = |index: usize| NodeHandle {
+            slot: index,
+            generation: 0,
+        };
+        let slots = flat_tree
+            .nodes
+            .iter()
+            .map(|node| Slot::Occupied {
+                generation: 0,
+                node: GenNode {
+                    name: node.name.clone(),
+                    depth: node.depth,
+                    parent: node.parent.map(handle_of),
+                    left_child: node.left_child.map(handle_of),
+                    right_child: node.right_child.map(handle_of),
+                },
+            })
+            .collect();
+        GenTree {
+            slots,
+            free_head: None,
+            root: handle_of(flat_tree.root),
+        }
+    }
+
+    /// Exports the live, root-reachable part of the arena back to a dense
+    /// `FlatTree`, the representation the Newick I/O boundary understands.
+    /// This implicitly drops any freed slots, so it never needs to be
+    /// preceded by an explicit `compact()`.
+    pub fn to_flat_tree(&self) -> FlatTree {
+        let live = self.live_handles();
+        let remap: HashMap<usize, usize> = live
+            .iter()
+            .enumerate()
+            .map(|(new_slot, handle)| (handle.slot, new_slot))
+            .collect();
+        let nodes = live
+            .iter()
+            .map(|handle| {
+                let node = self.get(*handle).expect("handle came from live_handles");
+                FlatNode {
+                    name: node.name.clone(),
+                    depth: node.depth,
+                    parent: node.parent.map(|h| remap[&h.slot]),
+                    left_child: node.left_child.map(|h| remap[&h.slot]),
+                    right_child: node.right_child.map(|h| remap[&h.slot]),
+                }
+            })
+            .collect();
+        FlatTree { nodes, root: 0 }
+    }
+
+    /// The designated root, tracked directly rather than rediscovered by
+    /// scanning for a parentless node.
+    pub fn root(&self) -> NodeHandle {
+        self.root
+    }
+
+    pub fn set_root(&mut self, handle: NodeHandle) {
+        self.root = handle;
+    }
+
+    pub fn get(&self, handle: NodeHandle) -> Option<&GenNode> {
+        match self.slots.get(handle.slot)? {
+            Slot::Occupied { generation, node } if *generation == handle.generation => Some(node),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, handle: NodeHandle) -> Option<&mut GenNode> {
+        match self.slots.get_mut(handle.slot)? {
+            Slot::Occupied { generation, node } if *generation == handle.generation => Some(node),
+            _ => None,
+        }
+    }
+
+    /// Allocates a new node, reusing a freed slot (at the generation `free`
+    /// already bumped it to) when one is available instead of always
+    /// growing the backing `Vec`.
+    pub fn insert(&mut self, node: GenNode) -> NodeHandle {
+        match self.free_head {
+            Some(slot) => {
+                let (generation, next_free) = match self.slots[slot] {
+                    Slot::Free {
+                        generation,
+                        next_free,
+                    } => (generation, next_free),
+                    Slot::Occupied { .. } => unreachable!("free list points at an occupied slot"),
+                };
+                self.free_head = next_free;
+                self.slots[slot] = Slot::Occupied { generation, node };
+                NodeHandle { slot, generation }
+            }
+            None => {
+                let slot = self.slots.len();
+                self.slots.push(Slot::Occupied { generation: 0, node });
+                NodeHandle { slot, generation: 0 }
+            }
+        }
+    }
+
+    /// Frees `handle`'s slot for reuse. A no-op if the handle is already
+    /// stale (generation mismatch), since there is nothing left to free.
+    pub fn free(&mut self, handle: NodeHandle) {
+        if let Some(Slot::Occupied { generation, .. }) = self.slots.get(handle.slot) {
+            if *generation == handle.generation {
+                self.slots[handle.slot] = Slot::Free {
+                    generation: generation + 1,
+                    next_free: self.free_head,
+                };
+                self.free_head = Some(handle.slot);
+            }
+        }
+    }
+
+    /// Looks up the (first, in arbitrary traversal order) live node named
+    /// `name`, for callers that only have names on hand, mirroring
+    /// `FlatTree`'s `iter(TraversalOrder::PreOrder).position(...)` lookups.
+    pub fn find_by_name(&self, name: &str) -> Option<NodeHandle> {
+        self.live_handles()
+            .into_iter()
+            .find(|&handle| self.get(handle).is_some_and(|node| node.name == name))
+    }
+
+    fn live_handles(&self) -> Vec<NodeHandle> {
+        let mut live = Vec::new();
+        let mut stack = vec![self.root];
+        while let Some(handle) = stack.pop() {
+            live.push(handle);
+            if let Some(node) = self.get(handle) {
+                stack.extend(node.left_child);
+                stack.extend(node.right_child);
+            }
+        }
+        live
+    }
+
+    /// Rebuilds the arena so it holds only the nodes still reachable from
+    /// the root, in a dense `0..len` slot range with no free list left to
+    /// walk. This is how a long-running simulation reclaims the slots
+    /// `free` has accumulated; it invalidates every handle taken before
+    /// the call (including the previous `root()`), since slots may move.
+    pub fn compact(&mut self) {
+        let live = self.live_handles();
+        let remap: HashMap<usize, usize> = live
+            .iter()
+            .enumerate()
+            .map(|(new_slot, handle)| (handle.slot, new_slot))
+            .collect();
+        let remap_handle = |h: Option<NodeHandle>| {
+            h.map(|h| NodeHandle {
+                slot: remap[&h.slot],
+                generation: 0,
+            })
+        };
+
+        let mut slots = Vec::with_capacity(live.len());
+        for handle in &live {
+            let node = self.get(*handle).expect("handle came from live_handles");
+            slots.push(Slot::Occupied {
+                generation: 0,
+                node: GenNode {
+                    name: node.name.clone(),
+                    depth: node.depth,
+                    parent: remap_handle(node.parent),
+                    left_child: remap_handle(node.left_child),
+                    right_child: remap_handle(node.right_child),
+                },
+            });
+        }
+
+        self.root = NodeHandle {
+            slot: remap[&self.root.slot],
+            generation: 0,
+        };
+        self.slots = slots;
+        self.free_head = None;
+    }
+
+    /// Alias for [`GenTree::compact`], for callers reaching for `gc` out of
+    /// habit from other arena-backed APIs.
+    pub fn gc(&mut self) {
+        self.compact();
+    }
+}
+
+/// Arena-backed counterpart of [`crate::spr`]: the same subtree-prune-and-
+/// regraft move, operating on `NodeHandle`s instead of raw indices. This
+/// binary-tree SPR move reuses every existing node as either the new
+/// graft point or an untouched internal node, so it never actually
+/// disconnects a node from the live topology — there is accordingly
+/// nothing for it to hand to `GenTree::free`, and it frees no slots.
+pub fn arena_spr(
+    tree: &mut GenTree,
+    donor: NodeHandle,
+    recipient: NodeHandle,
+    time: f64,
+) -> Result<(), SprError> {
+    if time.is_nan() {
+        return Err(SprError::InvalidTime);
+    }
+
+    let donor_parent = tree
+        .get(donor)
+        .ok_or_else(|| SprError::NodeNotFound(format!("{:?}", donor)))?
+        .parent
+        .ok_or(SprError::DonorIsRoot)?;
+    let recipient_parent = tree
+        .get(recipient)
+        .ok_or_else(|| SprError::NodeNotFound(format!("{:?}", recipient)))?
+        .parent
+        .ok_or(SprError::RecipientIsRoot)?;
+
+    if descends_from(tree, donor, recipient) {
+        return Err(SprError::DonorDescendsFromRecipient);
+    }
+
+    let recipient_parent_node = tree.get(recipient_parent).expect("looked up above");
+    let recipient_sibling = if recipient_parent_node.left_child == Some(recipient) {
+        recipient_parent_node.right_child.unwrap()
+    } else {
+        recipient_parent_node.left_child.unwrap()
+    };
+
+    let recipient_grandparent = tree.get(recipient_parent).expect("looked up above").parent;
+
+    if recipient_grandparent.is_none() {
+        // recipient_parent is the root: its sibling becomes the new root.
+        tree.get_mut(recipient_sibling).unwrap().parent = None;
+        tree.set_root(recipient_sibling);
+    } else if let Some(gp) = recipient_grandparent {
+        let gp_node = tree.get_mut(gp).unwrap();
+        if gp_node.left_child == Some(recipient_parent) {
+            gp_node.left_child = Some(recipient_sibling);
+        } else {
+            gp_node.right_child = Some(recipient_sibling);
+        }
+        tree.get_mut(recipient_sibling).unwrap().parent = Some(gp);
+    }
+
+    // recipient_parent becomes the new internal node at the graft point.
+    tree.get_mut(recipient_parent).unwrap().parent = Some(donor_parent);
+    {
+        let recipient_parent_node = tree.get_mut(recipient_parent).unwrap();
+        if recipient_parent_node.left_child == Some(recipient) {
+            recipient_parent_node.right_child = Some(donor);
+        } else {
+            recipient_parent_node.left_child = Some(donor);
+        }
+        recipient_parent_node.depth = Some(time);
+    }
+
+    let donor_parent_node = tree.get_mut(donor_parent).unwrap();
+    if donor_parent_node.left_child == Some(donor) {
+        donor_parent_node.left_child = Some(recipient_parent);
+    } else {
+        donor_parent_node.right_child = Some(recipient_parent);
+    }
+
+    tree.get_mut(donor).unwrap().parent = Some(recipient_parent);
+
+    Ok(())
+}
+
+fn descends_from(tree: &GenTree, descendant: NodeHandle, ancestor: NodeHandle) -> bool {
+    let mut current = tree.get(descendant).and_then(|node| node.parent);
+    while let Some(parent) = current {
+        if parent == ancestor {
+            return true;
+        }
+        current = tree.get(parent).and_then(|node| node.parent);
+    }
+    false
+}
@@ -0,0 +1,35 @@
+use std::fmt;
+
+/// Errors produced while attempting an SPR move on a `FlatTree`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SprError {
+    /// The donor node has no parent, i.e. it is the tree's root.
+    DonorIsRoot,
+    /// The recipient node has no parent, i.e. it is the tree's root.
+    RecipientIsRoot,
+    /// The donor is a descendant of the recipient, so regrafting the donor
+    /// onto the recipient would attach the donor's subtree under itself.
+    DonorDescendsFromRecipient,
+    /// A node looked up by name was not present in the tree.
+    NodeNotFound(String),
+    /// The requested transfer time cannot be used (e.g. it is NaN).
+    InvalidTime,
+}
+
+impl fmt::Display for SprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SprError::DonorIsRoot => write!(f, "the donor node is the root and has no parent"),
+            SprError::RecipientIsRoot => {
+                write!(f, "the recipient node is the root and has no parent")
+            }
+            SprError::DonorDescendsFromRecipient => {
+                write!(f, "the donor node descends from the recipient node")
+            }
+            SprError::NodeNotFound(name) => write!(f, "node '{}' not found in tree", name),
+            SprError::InvalidTime => write!(f, "the requested transfer time is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for SprError {}
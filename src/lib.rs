@@ -0,0 +1,161 @@
+use newick_parser::node::{FlatTree, TraversalOrder};
+
+mod arena;
+mod error;
+mod neighborhood;
+mod petgraph_interop;
+pub use arena::{arena_spr, GenNode, GenTree, NodeHandle};
+pub use error::SprError;
+pub use neighborhood::{spr_moves, spr_neighbors, SprMoves};
+pub use petgraph_interop::{FromPetgraph, PhyloGraph, PhyloNode, ToPetgraph};
+
+/// Emits a debug trace line through the `log` crate, compiled out entirely
+/// unless the `verbose` feature is enabled. This replaces the `println!`
+/// tracing `spr` used to do unconditionally, so embedding this crate in a
+/// tree-search loop doesn't flood stdout.
+macro_rules! spr_trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "verbose")]
+        {
+            log::debug!($($arg)*);
+        }
+    };
+}
+
+/// True if `descendant` has `ancestor` somewhere among its parents.
+fn descends_from(flat_tree: &FlatTree, descendant: usize, ancestor: usize) -> bool {
+    let mut current = flat_tree[descendant].parent;
+    while let Some(parent) = current {
+        if parent == ancestor {
+            return true;
+        }
+        current = flat_tree[parent].parent;
+    }
+    false
+}
+
+/// Performs a subtree-prune-and-regraft move in place: `recipient`'s
+/// subtree is pruned from its current position and regrafted onto the
+/// branch leading to `donor`, with the new internal node created at the
+/// graft point (the former parent of `recipient`, now relocated onto
+/// `donor`'s branch) given depth `time`. `donor` itself stays where it
+/// was; only its immediate parent changes, to the relocated node.
+///
+/// Returns an error instead of panicking for a NaN `time`, a root donor or
+/// recipient, or a donor that descends from the recipient, so callers can
+/// embed this in a search loop without risking a panic on those moves.
+/// Other degenerate pairs (a donor equal to the recipient, to its parent,
+/// or to its sibling) are not checked and leave the tree inconsistent.
+pub fn spr(
+    flat_tree: &mut FlatTree,
+    donor: usize,
+    recipient: usize,
+    time: f64,
+) -> Result<(), SprError> {
+    if time.is_nan() {
+        return Err(SprError::InvalidTime);
+    }
+
+    let donor_parent = flat_tree[donor].parent.ok_or(SprError::DonorIsRoot)?;
+    let recipient_parent = flat_tree[recipient]
+        .parent
+        .ok_or(SprError::RecipientIsRoot)?;
+
+    if descends_from(flat_tree, donor, recipient) {
+        return Err(SprError::DonorDescendsFromRecipient);
+    }
+
+    spr_trace!("SPR start: donor={} recipient={}", donor, recipient);
+    spr_trace!("  donor_parent={} recipient_parent={}", donor_parent, recipient_parent);
+
+    let recipient_sibling = if flat_tree[recipient_parent].left_child.unwrap() == recipient {
+        flat_tree[recipient_parent].right_child.unwrap()
+    } else {
+        flat_tree[recipient_parent].left_child.unwrap()
+    };
+    spr_trace!("  recipient_sibling={}", recipient_sibling);
+
+    if flat_tree[recipient_parent].parent.is_none() {
+        spr_trace!("  recipient_parent {} is the root", recipient_parent);
+        // The recipient's sibling becomes the new root.
+        flat_tree[recipient_sibling].parent = None;
+        flat_tree.root = recipient_sibling;
+
+        // Reassign the recipient's parent: attach it under the donor's parent.
+        flat_tree[recipient_parent].parent = Some(donor_parent);
+
+        // Update the child pointer in recipient_parent: replace the
+        // recipient's sibling with the donor.
+        if flat_tree[recipient_parent].left_child.unwrap() == recipient {
+            flat_tree[recipient_parent].right_child = Some(donor);
+        } else {
+            flat_tree[recipient_parent].left_child = Some(donor);
+        }
+        flat_tree[recipient_parent].depth = Some(time);
+
+        // Update donor_parent so that its child pointer now points to recipient_parent.
+        if flat_tree[donor_parent].left_child.unwrap() == donor {
+            flat_tree[donor_parent].left_child = Some(recipient_parent);
+        } else {
+            flat_tree[donor_parent].right_child = Some(recipient_parent);
+        }
+        // Finally, attach the donor under recipient_parent.
+        flat_tree[donor].parent = Some(recipient_parent);
+    } else {
+        // Normal case: recipient_parent is not the root.
+        let recipient_grandparent = flat_tree[recipient_parent].parent;
+
+        flat_tree[recipient_parent].parent = Some(donor_parent);
+        // Replace recipient_parent's OTHER slot (the one holding
+        // recipient_sibling) with donor, leaving recipient's own slot
+        // untouched — recipient_parent keeps recipient as a child and picks
+        // up donor as its sibling. Swapping which slot gets overwritten
+        // here detached recipient instead (it kept pointing at
+        // recipient_parent as its parent, but recipient_parent no longer
+        // listed it), corrupting any move where recipient_parent wasn't the
+        // root.
+        if flat_tree[recipient_parent].left_child.unwrap() == recipient {
+            flat_tree[recipient_parent].right_child = Some(donor);
+        } else {
+            flat_tree[recipient_parent].left_child = Some(donor);
+        }
+        flat_tree[recipient_parent].depth = Some(time);
+
+        if let Some(gp) = recipient_grandparent {
+            if flat_tree[gp].left_child.unwrap() == recipient_parent {
+                flat_tree[gp].left_child = Some(recipient_sibling);
+            } else {
+                flat_tree[gp].right_child = Some(recipient_sibling);
+            }
+            flat_tree[recipient_sibling].parent = Some(gp);
+        }
+        if flat_tree[donor_parent].left_child.unwrap() == donor {
+            flat_tree[donor_parent].left_child = Some(recipient_parent);
+        } else {
+            flat_tree[donor_parent].right_child = Some(recipient_parent);
+        }
+        flat_tree[donor].parent = Some(recipient_parent);
+    }
+
+    spr_trace!("SPR end");
+    Ok(())
+}
+
+/// Convenience wrapper around `spr` that resolves the donor and recipient
+/// by name instead of by index, for callers that only have names on hand.
+pub fn by_name(
+    flat_tree: &mut FlatTree,
+    donor_name: &str,
+    recipient_name: &str,
+    time: f64,
+) -> Result<(), SprError> {
+    let donor = flat_tree
+        .iter(TraversalOrder::PreOrder)
+        .position(|node| node.name == donor_name)
+        .ok_or_else(|| SprError::NodeNotFound(donor_name.to_string()))?;
+    let recipient = flat_tree
+        .iter(TraversalOrder::PreOrder)
+        .position(|node| node.name == recipient_name)
+        .ok_or_else(|| SprError::NodeNotFound(recipient_name.to_string()))?;
+    spr(flat_tree, donor, recipient, time)
+}
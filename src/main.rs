@@ -1,197 +1,127 @@
-use newick_parser::newick::{node_to_newick_no_lengths, newick_to_tree, NewickParser, Rule};
-use newick_parser::node::{FlatTree, TraversalOrder};
+use newick_parser::newick::{newick_to_tree, NewickParser, Rule};
+use newick_parser::node::{FlatTree, Node};
 use pest::Parser;
+use spr::{arena_spr, GenTree, NodeHandle};
 use std::env;
 use std::fs;
 
-/// The updated SPR function with debug print statements.
-pub fn spr(
-    flat_tree: &mut FlatTree,
-    donor: usize,
-    recipient: usize,
-    time: f64,
-) {
-    // Get initial state
-    let donor_parent = flat_tree[donor]
-        .parent
-        .expect("The donor node should not be the root");
-    let recipient_parent = flat_tree[recipient]
-        .parent
-        .expect("The recipient node should not be the root");
-
-    println!("SPR Start:");
-    println!("  donor: {} (parent: {})", donor, donor_parent);
-    println!("  recipient: {} (parent: {})", recipient, recipient_parent);
-
-    let recipient_sibling = if flat_tree[recipient_parent].left_child.unwrap() == recipient {
-        flat_tree[recipient_parent].right_child.unwrap()
-    } else {
-        flat_tree[recipient_parent].left_child.unwrap()
-    };
-    println!("  recipient_sibling: {}", recipient_sibling);
-
-    // Check if recipient's parent is the root
-    // Show current values of variables
-    println!("  Recipient's parent's parent: {:?}", flat_tree[recipient_parent].parent);
-    println!("  Recipient's parent left child: {:?}", flat_tree[recipient_parent].left_child);
-    println!("  Recipient's parent right child: {:?}", flat_tree[recipient_parent].right_child);
-    println!("  Recipient sibling: {:?}", recipient_sibling);
-    println!("  Recipient sibling parent: {:?}", flat_tree[recipient_sibling].parent);
-    println!("  Donor parent: {:?}", flat_tree[donor_parent].parent);
-    if flat_tree[recipient_parent].parent.is_none() {
-        println!("  Recipient's parent {} is the root.", recipient_parent);
-        // The recipient's sibling becomes the new root.
-        flat_tree[recipient_sibling].parent = None;
-        flat_tree.root = recipient_sibling;
-        println!("  New root set to recipient_sibling: {}", recipient_sibling);
-
-        // Reassign the recipient's parent: attach it under the donor's parent.
-        flat_tree[recipient_parent].parent = Some(donor_parent);
-        println!("  Set recipient_parent {}'s parent to donor_parent {}", recipient_parent, donor_parent);
-
-        // Update the child pointer in recipient_parent: replace the recipient's parent's sister with the donor.
-        if flat_tree[recipient_parent].left_child.unwrap() == recipient {
-            flat_tree[recipient_parent].right_child = Some(donor);
-        } else {
-            flat_tree[recipient_parent].left_child = Some(donor);
-        }
-        println!("  In recipient_parent {}, replaced child {} with donor {}", recipient_parent, recipient, donor);
-        flat_tree[recipient_parent].depth = Some(time);
-        println!("  Set recipient_parent {} depth to {}", recipient_parent, time);
-
-        // Update donor_parent so that its child pointer now points to recipient_parent.
-        if flat_tree[donor_parent].left_child.unwrap() == donor {
-            flat_tree[donor_parent].left_child = Some(recipient_parent);
-        } else {
-            flat_tree[donor_parent].right_child = Some(recipient_parent);
-        }
-        println!("  In donor_parent {}, replaced child {} with recipient_parent {}", donor_parent, donor, recipient_parent);
-        // Finally, attach the donor under recipient_parent.
-        flat_tree[donor].parent = Some(recipient_parent);
-        println!("  Set donor {}'s parent to recipient_parent {}", donor, recipient_parent);
-    } else {
-        // Normal case: recipient_parent is not the root.
-        let recipient_grandparent = flat_tree[recipient_parent].parent;
-        println!("  Recipient's parent {} is not the root. Grandparent: {:?}", recipient_parent, recipient_grandparent);
-
-        flat_tree[recipient_parent].parent = Some(donor_parent);
-        println!("  Set recipient_parent {}'s parent to donor_parent {}", recipient_parent, donor_parent);
-        if flat_tree[recipient_parent].left_child.unwrap() == recipient {
-            flat_tree[recipient_parent].left_child = Some(donor);
-        } else {
-            flat_tree[recipient_parent].right_child = Some(donor);
-        }
-        println!("  In recipient_parent {}, replaced child {} with donor {}", recipient_parent, recipient, donor);
-        flat_tree[recipient_parent].depth = Some(time);
-        println!("  Set recipient_parent {} depth to {}", recipient_parent, time);
-
-        if let Some(gp) = recipient_grandparent {
-            println!("  Updating recipient_grandparent {} for recipient_parent {}", gp, recipient_parent);
-            if flat_tree[gp].left_child.unwrap() == recipient_parent {
-                flat_tree[gp].left_child = Some(recipient_sibling);
-            } else {
-                flat_tree[gp].right_child = Some(recipient_sibling);
+/// Branch-length-aware counterpart of `node_to_newick_no_lengths`: each
+/// branch length is reconstructed as the difference between a node's depth
+/// and its parent's depth, since `spr` only ever updates `depth` and never
+/// maintains an explicit length field. A node (or its parent) missing a
+/// depth falls back to a lengthless `name` token rather than failing.
+fn node_to_newick_with_lengths(node: &Node) -> String {
+    fn walk(node: &Node, parent_depth: Option<f64>) -> String {
+        let body = match (&node.left_child, &node.right_child) {
+            (Some(left), Some(right)) => format!(
+                "({},{})",
+                walk(left, node.depth),
+                walk(right, node.depth)
+            ),
+            _ => String::new(),
+        };
+        match (node.depth, parent_depth) {
+            (Some(depth), Some(parent_depth)) => {
+                format!("{}{}:{}", body, node.name, depth - parent_depth)
             }
-            println!("  In grandparent {}, replaced child {} with recipient_sibling {}", gp, recipient_parent, recipient_sibling);
-            flat_tree[recipient_sibling].parent = Some(gp);
-            println!("  Set recipient_sibling {}'s parent to grandparent {}", recipient_sibling, gp);
-        }
-        if flat_tree[donor_parent].left_child.unwrap() == donor {
-            flat_tree[donor_parent].left_child = Some(recipient_parent);
-        } else {
-            flat_tree[donor_parent].right_child = Some(recipient_parent);
+            _ => format!("{}{}", body, node.name),
         }
-        println!("  In donor_parent {}, replaced child {} with recipient_parent {}", donor_parent, donor, recipient_parent);
-        flat_tree[donor].parent = Some(recipient_parent);
-        println!("  Set donor {}'s parent to recipient_parent {}", donor, recipient_parent);
     }
+    walk(node, None)
+}
 
-    println!("SPR End.");
+/// `FlatTree`-level helper so callers can go straight from the flat,
+/// index-based representation `spr` operates on to branch-length Newick
+/// without reconstructing the recursive gene tree themselves first.
+trait ToNewickWithLengths {
+    fn to_newick_with_lengths(&self) -> String;
 }
 
-fn main() {
-    // Expect four arguments: tree file, donor name, recipient name, and output file.
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 5 {
-        eprintln!(
-            "Usage: {} <tree_file> <donor> <recipient> <output_file>",
-            args[0]
-        );
-        return;
+impl ToNewickWithLengths for FlatTree {
+    fn to_newick_with_lengths(&self) -> String {
+        node_to_newick_with_lengths(&self.to_node())
     }
+}
 
-    let tree_file = &args[1];
-    let donor_name = &args[2];
-    let recipient_name = &args[3];
-    let output_file = &args[4];
+/// One donor/recipient/time row parsed from a transfer-events file.
+struct TransferEvent {
+    donor: String,
+    recipient: String,
+    time: f64,
+}
 
-    // Read and sanitize the tree (expecting Newick format ending with a semicolon)
-    let tree_str = fs::read_to_string(tree_file).expect("Failed to read tree file");
-    let sanitized = tree_str.trim();
-    let trees: Vec<String> = sanitized
-        .split(';')
-        .filter_map(|s| {
-            let s = s.trim();
-            if s.is_empty() {
-                None
-            } else {
-                Some(format!("{};", s))
+/// Parses a transfer-events file: one `donor recipient time` triple per
+/// non-empty, non-comment line. Blank lines and lines starting with `#`
+/// are skipped so event files can carry a header/comments.
+fn parse_transfer_events(path: &str) -> Vec<TransferEvent> {
+    let contents = fs::read_to_string(path).expect("Failed to read transfers file");
+    contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
             }
+            let mut fields = line.split_whitespace();
+            let donor = fields
+                .next()
+                .unwrap_or_else(|| panic!("Malformed transfer line: '{}'", line));
+            let recipient = fields
+                .next()
+                .unwrap_or_else(|| panic!("Malformed transfer line: '{}'", line));
+            let time: f64 = fields
+                .next()
+                .unwrap_or_else(|| panic!("Malformed transfer line: '{}'", line))
+                .parse()
+                .unwrap_or_else(|_| panic!("Malformed transfer time in line: '{}'", line));
+            Some(TransferEvent {
+                donor: donor.to_string(),
+                recipient: recipient.to_string(),
+                time,
+            })
         })
-        .collect();
+        .collect()
+}
 
-    if trees.is_empty() {
-        eprintln!("No tree found in file.");
-        return;
-    }
+/// A branch leading to `node` is alive only on the open interval between
+/// its parent's depth and its own depth. A transfer at `time` can only use
+/// this branch as donor or recipient if `time` falls strictly inside that
+/// interval; the root (no parent) and nodes with an unset depth have no
+/// alive branch at all.
+fn branch_is_alive_at(tree: &GenTree, node: NodeHandle, time: f64) -> bool {
+    let node = match tree.get(node) {
+        Some(node) => node,
+        None => return false,
+    };
+    let node_depth = match node.depth {
+        Some(depth) => depth,
+        None => return false,
+    };
+    let parent = match node.parent.and_then(|parent| tree.get(parent)) {
+        Some(parent) => parent,
+        None => return false,
+    };
+    let parent_depth = match parent.depth {
+        Some(depth) => depth,
+        None => return false,
+    };
+    time > parent_depth && time < node_depth
+}
 
-    // Use the first tree found in the file.
-    let tree_newick = &trees[0];
-    let pairs = NewickParser::parse(Rule::newick, tree_newick)
-        .expect("Failed to parse Newick tree");
-    let mut node_tree = newick_to_tree(pairs.into_iter().next().unwrap())
-        .pop()
-        .expect("No tree produced");
-    let mut flat_tree = node_tree.to_flat_tree();
-
-    // Locate donor and recipient nodes by name.
-    let donor_index = flat_tree
-        .iter(TraversalOrder::PreOrder)
-        .position(|node| node.name == *donor_name)
-        .unwrap_or_else(|| panic!("Donor '{}' not found in tree", donor_name));
-    let recipient_index = flat_tree
-        .iter(TraversalOrder::PreOrder)
-        .position(|node| node.name == *recipient_name)
-        .unwrap_or_else(|| panic!("Recipient '{}' not found in tree", recipient_name));
-
-    // Prevent invalid moves: donor must not be a descendant of the recipient.
-    let mut current = flat_tree[donor_index].parent;
-    while let Some(parent) = current {
-        if parent == recipient_index {
-            eprintln!(
-                "Invalid SPR: donor '{}' is a descendant of recipient '{}'",
-                donor_name, recipient_name
-            );
-            std::process::exit(1);
-        }
-        current = flat_tree[parent].parent;
+// Helper function to format Option<T> as a string.
+fn fmt_option<T: std::fmt::Display>(opt: Option<T>) -> String {
+    match opt {
+        Some(val) => format!("{}", val),
+        None => String::from("None"),
     }
-    // Helper function to format Option<T> as a string.
-    fn fmt_option<T: std::fmt::Display>(opt: Option<T>) -> String {
-        match opt {
-            Some(val) => format!("{}", val),
-            None => String::from("None"),
-        }
-    }
-    // Debug print: flat tree before SPR.
-    println!("--- Flat tree BEFORE SPR ---");
-    // Print a header for the table.
+}
+
+fn print_flat_tree(label: &str, flat_tree: &FlatTree) {
+    println!("--- Flat tree {} ---", label);
     println!(
         "{:<6} {:<15} {:<10} {:<10} {:<10} {:<10}",
         "Index", "Name", "Parent", "Left", "Right", "Depth"
     );
-
-    // Iterate over the flat_tree vector (in its natural order) and print each node's details.
     for (i, node) in flat_tree.nodes.iter().enumerate() {
         println!(
             "{:<6} {:<15} {:<10} {:<10} {:<10} {:<10}",
@@ -206,50 +136,150 @@ fn main() {
             }
         );
     }
+}
 
-    // Apply the SPR event with a fixed time (0.5).
-    spr(&mut flat_tree, donor_index, recipient_index, 0.5);
+/// Applies every transfer event to a single tree in the forest, in the
+/// order given (`main` sorts them by time first). Donor/recipient names are matched independently
+/// per tree, and an event whose donor or recipient is missing from this
+/// particular tree is skipped (and reported) rather than aborting the
+/// whole run, so a gene tree that is missing a taxon doesn't take down the
+/// rest of the forest.
+fn apply_events_to_tree(tree: &mut GenTree, events: &[TransferEvent], tree_label: &str) {
+    for event in events {
+        let donor_handle = match tree.find_by_name(&event.donor) {
+            Some(handle) => handle,
+            None => {
+                eprintln!(
+                    "[{}] Skipping transfer {} -> {} at t={}: donor not found in this tree",
+                    tree_label, event.donor, event.recipient, event.time
+                );
+                continue;
+            }
+        };
+        let recipient_handle = match tree.find_by_name(&event.recipient) {
+            Some(handle) => handle,
+            None => {
+                eprintln!(
+                    "[{}] Skipping transfer {} -> {} at t={}: recipient not found in this tree",
+                    tree_label, event.donor, event.recipient, event.time
+                );
+                continue;
+            }
+        };
 
-    // Update the root in case the topology has changed.
-    let root_index = flat_tree.nodes
-        .iter()
-        .position(|node| node.parent.is_none())
-        .expect("No root found in the tree");
-    flat_tree.root = root_index;
-    // Debug print: flat tree after SPR.
-    // Print the flat_tree vector in a table format.
-    // show root index
-    println!("Root index: {}", root_index);
-    println!("--- Flat tree after SPR (vector order) ---");
-    // Print a header for the table.
-    println!(
-        "{:<6} {:<15} {:<10} {:<10} {:<10} {:<10}",
-        "Index", "Name", "Parent", "Left", "Right", "Depth"
-    );
+        // Reject transfers that are not temporally feasible: both the donor
+        // and recipient branches must actually be alive at `event.time`.
+        if !branch_is_alive_at(tree, donor_handle, event.time) {
+            eprintln!(
+                "[{}] Skipping transfer {} -> {} at t={}: donor branch is not alive at that time",
+                tree_label, event.donor, event.recipient, event.time
+            );
+            continue;
+        }
+        if !branch_is_alive_at(tree, recipient_handle, event.time) {
+            eprintln!(
+                "[{}] Skipping transfer {} -> {} at t={}: recipient branch is not alive at that time",
+                tree_label, event.donor, event.recipient, event.time
+            );
+            continue;
+        }
 
-    // Iterate over the flat_tree vector (in its natural order) and print each node's details.
-    for (i, node) in flat_tree.nodes.iter().enumerate() {
+        // `arena_spr` sets the depth of the internal node created at the
+        // transfer point (the former recipient_parent) to `event.time`,
+        // which is what later calls to `branch_is_alive_at` validate
+        // against, and updates `tree`'s root in place when the topology
+        // changes, so there is no rescan for a parentless node here.
+        if let Err(e) = arena_spr(tree, donor_handle, recipient_handle, event.time) {
+            eprintln!(
+                "[{}] Skipping transfer {} -> {} at t={}: {}",
+                tree_label, event.donor, event.recipient, event.time, e
+            );
+            continue;
+        }
         println!(
-            "{:<6} {:<15} {:<10} {:<10} {:<10} {:<10}",
-            i,
-            node.name,
-            fmt_option(node.parent),
-            fmt_option(node.left_child),
-            fmt_option(node.right_child),
-            match node.depth {
-                Some(d) => format!("{:.2}", d),
-                None => String::from("None"),
-            }
+            "[{}] Applied transfer {} -> {} at t={}",
+            tree_label, event.donor, event.recipient, event.time
         );
     }
+}
 
+fn main() {
+    // Expect three arguments: tree file, transfer-events file, and output file.
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 4 {
+        eprintln!(
+            "Usage: {} <tree_file> <transfers_file> <output_file>",
+            args[0]
+        );
+        return;
+    }
+
+    let tree_file = &args[1];
+    let transfers_file = &args[2];
+    let output_file = &args[3];
+
+    // Read and sanitize the tree file, which may hold a forest of gene
+    // trees (one Newick string per `;`-terminated entry) rather than a
+    // single tree.
+    let tree_str = fs::read_to_string(tree_file).expect("Failed to read tree file");
+    let sanitized = tree_str.trim();
+    let tree_newicks: Vec<String> = sanitized
+        .split(';')
+        .filter_map(|s| {
+            let s = s.trim();
+            if s.is_empty() {
+                None
+            } else {
+                Some(format!("{};", s))
+            }
+        })
+        .collect();
 
-    //panic!("Debug");
+    if tree_newicks.is_empty() {
+        eprintln!("No tree found in file.");
+        return;
+    }
 
-    // Reconstruct the node tree and update branch lengths based on node depths.
-    let gene_tree = flat_tree.to_node();
+    // Parse every tree in the file into its own flat tree, then import each
+    // into arena-backed storage: `apply_events_to_tree` drives the transfer
+    // loop on `GenTree` so it tracks the root directly instead of
+    // rescanning for a parentless node after every transfer.
+    let mut forest: Vec<GenTree> = tree_newicks
+        .iter()
+        .map(|tree_newick| {
+            let pairs = NewickParser::parse(Rule::newick, tree_newick)
+                .expect("Failed to parse Newick tree");
+            let mut node_tree = newick_to_tree(pairs.into_iter().next().unwrap())
+                .pop()
+                .expect("No tree produced");
+            GenTree::from_flat_tree(&node_tree.to_flat_tree())
+        })
+        .collect();
+
+    // Load the batch of transfer events and apply them in strictly
+    // increasing time order, since later events must validate against the
+    // timeline left behind by earlier ones.
+    let mut events = parse_transfer_events(transfers_file);
+    events.sort_by(|a, b| {
+        a.time
+            .partial_cmp(&b.time)
+            .unwrap_or_else(|| panic!("Transfer time is not comparable (NaN?)"))
+    });
 
-    // Convert the modified tree to Newick format and write it to the output file.
-    let newick = node_to_newick_no_lengths(&gene_tree) + ";";
-    fs::write(output_file, newick).expect("Failed to write gene tree to file");
+    for (i, tree) in forest.iter_mut().enumerate() {
+        let tree_label = format!("tree {}/{}", i + 1, tree_newicks.len());
+        print_flat_tree(&format!("{} BEFORE transfers", tree_label), &tree.to_flat_tree());
+        apply_events_to_tree(tree, &events, &tree_label);
+        print_flat_tree(&format!("{} AFTER transfers", tree_label), &tree.to_flat_tree());
+    }
+
+    // Convert every tree back to Newick format, preserving branch lengths
+    // derived from the node depths `arena_spr` maintained, and write the
+    // whole forest out as a multi-line Newick file.
+    let newick = forest
+        .iter()
+        .map(|tree| tree.to_flat_tree().to_newick_with_lengths() + ";")
+        .collect::<Vec<_>>()
+        .join("\n");
+    fs::write(output_file, newick).expect("Failed to write gene trees to file");
 }
@@ -0,0 +1,121 @@
+use newick_parser::node::FlatTree;
+
+use crate::spr;
+
+/// True if `candidate` is an ancestor of `node` (including `node`'s direct
+/// parent), by walking parent pointers from `node` up to the root.
+fn is_ancestor_of(flat_tree: &FlatTree, candidate: usize, node: usize) -> bool {
+    let mut current = flat_tree[node].parent;
+    while let Some(parent) = current {
+        if parent == candidate {
+            return true;
+        }
+        current = flat_tree[parent].parent;
+    }
+    false
+}
+
+/// `spr_neighbors` replays each `(p, r)` pair as `spr(donor = p, recipient =
+/// r)`, and `spr` relocates the *recipient* subtree onto the *donor*
+/// branch: `r` is the subtree that actually gets pruned, and `p` is the
+/// branch it gets regrafted next to. A pair forms a valid single-SPR move
+/// when: `p` is not the root (`spr`'s donor needs a parent edge to regraft
+/// onto), `r` is not the root (there is no edge above it to prune), `r` is
+/// not `p` itself or an ancestor of `p` (`p` has to remain in the tree that
+/// is left once `r` is pruned — mirrors `spr`'s own
+/// `DonorDescendsFromRecipient` rejection), `p` is not `r`'s direct parent
+/// (reattaching `r`'s subtree immediately above itself makes `p` its own
+/// child, a cycle `spr` can't perform), and `r` is not `p`'s current
+/// sibling edge, which would just reproduce the original topology.
+///
+/// `p` being a *non-direct* ancestor of `r` (e.g. `r`'s grandparent) is
+/// deliberately not rejected: it is a legal move, and `spr` contracts the
+/// vacated edge correctly in that case too.
+fn is_valid_move(flat_tree: &FlatTree, p: usize, r: usize) -> bool {
+    let p_parent = match flat_tree[p].parent {
+        Some(parent) => parent,
+        None => return false,
+    };
+    if r == p || flat_tree[r].parent.is_none() {
+        return false;
+    }
+    if is_ancestor_of(flat_tree, r, p) || flat_tree[r].parent == Some(p) {
+        return false;
+    }
+    let sibling = if flat_tree[p_parent].left_child == Some(p) {
+        flat_tree[p_parent].right_child
+    } else {
+        flat_tree[p_parent].left_child
+    };
+    sibling != Some(r)
+}
+
+/// Lazily enumerates every valid single-SPR move on a `FlatTree` as
+/// `(donor, recipient)` index pairs that `spr`/`by_name` can replay. Note
+/// that `spr`'s `donor` is the regraft target and its `recipient` is the
+/// subtree actually pruned, so every non-root node is tried as a regraft
+/// target against every other node as a prune candidate.
+///
+/// This makes the neighborhood `O(n^2)` in the number of nodes `n` (before
+/// the constant-factor filtering of invalid and sibling-reproducing moves)
+/// — worth keeping in mind when using this as the move generator inside a
+/// tree search over a tree with many taxa.
+pub struct SprMoves<'a> {
+    flat_tree: &'a FlatTree,
+    regraft_target: usize,
+    prune_candidate: usize,
+}
+
+impl<'a> SprMoves<'a> {
+    pub fn new(flat_tree: &'a FlatTree) -> Self {
+        SprMoves {
+            flat_tree,
+            regraft_target: 0,
+            prune_candidate: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for SprMoves<'a> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node_count = self.flat_tree.nodes.len();
+        loop {
+            if self.regraft_target >= node_count {
+                return None;
+            }
+            if self.prune_candidate >= node_count {
+                self.regraft_target += 1;
+                self.prune_candidate = 0;
+                continue;
+            }
+            let p = self.regraft_target;
+            let r = self.prune_candidate;
+            self.prune_candidate += 1;
+            if is_valid_move(self.flat_tree, p, r) {
+                return Some((p, r));
+            }
+        }
+    }
+}
+
+/// Convenience entry point for [`SprMoves`].
+pub fn spr_moves(flat_tree: &FlatTree) -> SprMoves<'_> {
+    SprMoves::new(flat_tree)
+}
+
+/// Lazily enumerates every single-SPR neighbor topology of `flat_tree` as
+/// cloned, already-rearranged `FlatTree`s, for callers that want complete
+/// trees rather than move descriptors to replay themselves. The pruned
+/// subtree's (`recipient`'s) own depth is reused as the transfer time,
+/// since a topology-only neighbor search has no other meaningful time to
+/// assign.
+pub fn spr_neighbors(flat_tree: &FlatTree) -> impl Iterator<Item = FlatTree> + '_ {
+    spr_moves(flat_tree).filter_map(move |(donor, recipient)| {
+        let mut neighbor = flat_tree.clone();
+        let time = neighbor[recipient].depth.unwrap_or(0.0);
+        spr(&mut neighbor, donor, recipient, time).ok()?;
+        Some(neighbor)
+    })
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use newick_parser::node::{FlatNode, FlatTree};
+use petgraph::stable_graph::{NodeIndex, StableGraph};
+use petgraph::Direction;
+
+/// Node weight used when exporting a `FlatTree` to petgraph: just enough
+/// to reconstruct a `FlatTree` on the way back in via [`FromPetgraph`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhyloNode {
+    pub name: String,
+    pub depth: Option<f64>,
+}
+
+/// A phylogenetic tree as a petgraph graph: parent -> child directed
+/// edges, no edge weights.
+pub type PhyloGraph = StableGraph<PhyloNode, ()>;
+
+/// Converts a `FlatTree` into a petgraph `StableGraph`, so it can be fed
+/// into the wider ecosystem of petgraph algorithms (connectivity checks,
+/// LCA, shortest paths for patristic distance, ...).
+pub trait ToPetgraph {
+    /// Returns the graph along with the `NodeIndex` of the designated
+    /// root, so callers don't need to rediscover it by scanning for an
+    /// indegree-0 node.
+    fn to_petgraph(&self) -> (PhyloGraph, NodeIndex);
+}
+
+impl ToPetgraph for FlatTree {
+    fn to_petgraph(&self) -> (PhyloGraph, NodeIndex) {
+        let mut graph = PhyloGraph::new();
+        let indices: Vec<NodeIndex> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                graph.add_node(PhyloNode {
+                    name: node.name.clone(),
+                    depth: node.depth,
+                })
+            })
+            .collect();
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            if let Some(left) = node.left_child {
+                graph.add_edge(indices[i], indices[left], ());
+            }
+            if let Some(right) = node.right_child {
+                graph.add_edge(indices[i], indices[right], ());
+            }
+        }
+
+        (graph, indices[self.root])
+    }
+}
+
+/// Builds a `FlatTree` back out of a petgraph graph produced by
+/// [`ToPetgraph::to_petgraph`] (or assembled by some other bio crate that
+/// already standardizes on petgraph).
+pub trait FromPetgraph: Sized {
+    /// `root` must be a node index of `graph` with no incoming edges.
+    /// Each node's first outgoing edge (in petgraph's iteration order)
+    /// becomes `left_child`, the second becomes `right_child` — an
+    /// arbitrary but consistent choice, since topology doesn't distinguish
+    /// left from right.
+    fn from_petgraph(graph: &PhyloGraph, root: NodeIndex) -> Self;
+}
+
+impl FromPetgraph for FlatTree {
+    fn from_petgraph(graph: &PhyloGraph, root: NodeIndex) -> Self {
+        let index_order: Vec<NodeIndex> = graph.node_indices().collect();
+        let flat_index_of: HashMap<NodeIndex, usize> = index_order
+            .iter()
+            .enumerate()
+            .map(|(flat_index, &idx)| (idx, flat_index))
+            .collect();
+
+        let mut nodes: Vec<FlatNode> = index_order
+            .iter()
+            .map(|&idx| {
+                let weight = &graph[idx];
+                FlatNode {
+                    name: weight.name.clone(),
+                    depth: weight.depth,
+                    parent: None,
+                    left_child: None,
+                    right_child: None,
+                }
+            })
+            .collect();
+
+        for &idx in &index_order {
+            let flat_index = flat_index_of[&idx];
+            let mut children = graph.neighbors_directed(idx, Direction::Outgoing);
+            if let Some(left) = children.next() {
+                let left_flat = flat_index_of[&left];
+                nodes[flat_index].left_child = Some(left_flat);
+                nodes[left_flat].parent = Some(flat_index);
+            }
+            if let Some(right) = children.next() {
+                let right_flat = flat_index_of[&right];
+                nodes[flat_index].right_child = Some(right_flat);
+                nodes[right_flat].parent = Some(flat_index);
+            }
+        }
+
+        FlatTree {
+            root: flat_index_of[&root],
+            nodes,
+        }
+    }
+}